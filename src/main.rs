@@ -1,29 +1,53 @@
+mod metrics;
+mod ping_backend;
 mod ping_result;
+mod target_config;
+mod target_manager;
 
 #[macro_use]
 extern crate log;
 extern crate simple_logger;
 
-use prometheus_exporter_base::{MetricType, PrometheusMetric};
-
 use std::fs;
 use std::path::PathBuf;
-use std::process::Command;
 use std::result::Result;
+use std::str::FromStr;
 use std::sync::{Arc};
 use std::thread;
-use std::time::{Duration, Instant};
 
 use actix_web::{middleware, web, App, HttpResponse, HttpServer, Responder};
-use hashbrown::HashMap;
-use ipnet::IpNet;
 use log::Level;
-use parking_lot::RwLock;
-use ping_result::PingResult;
-use rand::{thread_rng, Rng};
+use metrics::ResultStore;
+use ping_backend::{FpingBackend, IcmpBackend, PingBackend};
 use serde_derive::Deserialize;
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
 use structopt::StructOpt;
 use structopt_toml::StructOptToml;
+use target_config::TargetConfig;
+use target_manager::TargetManager;
+
+/// Which `PingBackend` drives the probes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Backend {
+    /// Shell out to the `fping` binary.
+    Fping,
+    /// Drive probes in-process with a pure-Rust async ICMP engine.
+    Icmp,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fping" => Ok(Backend::Fping),
+            "icmp" => Ok(Backend::Icmp),
+            other => Err(format!("unknown backend `{}`, expected `fping` or `icmp`", other)),
+        }
+    }
+}
 
 #[derive(Debug, Deserialize, StructOpt, StructOptToml)]
 #[serde(default)]
@@ -41,42 +65,24 @@ struct Opt {
     #[structopt(short, long = "config-path", parse(from_os_str), default_value = "/etc/fping_exporter/fping_exporter.toml")]
     config_path: PathBuf,
 
-    /// IP subnets
-    #[structopt(short, long)]
-    targets: Vec<IpNet>,
-}
-
-struct ResultStore {
-    ping_results: Arc<RwLock<HashMap<String, Vec<PingResult>>>>
-}
-
-fn process_subnet(target_subnet: IpNet) -> Result<Vec<PingResult>, String> {
-    let subnet_string = format!("{:?}", target_subnet);
-
-    let output = Command::new("/usr/local/sbin/fping")
-        .args(&["-q", "-r", "0", "-c", "5", "-g", &subnet_string])
-        .output()
-        .unwrap();
-
-    let stderr = String::from_utf8_lossy(&output.stderr);
-
-    // fping uses '4' to indicate some issue with running the command
-    if output.status.code() == Some(4) {
-        return Err(stderr.to_string())
-    }
-
-    let mut results = vec![];
-
-    for result in stderr.lines() {
-        match result.parse() {
-            Ok(ping_result) => results.push(ping_result),
-            Err(e) => error!("{}", e),
-        }
-        // let ping_result: PingResult = result.parse().unwrap();
-        // results.push(ping_result)
-    }
-
-    Ok(results)
+    /// Target subnets and their per-target scrape settings. Only configurable
+    /// via the `[[targets]]` table in the TOML config file, since each entry
+    /// carries its own probe count/interval/timeout/scrape period/labels.
+    #[structopt(skip)]
+    targets: Vec<TargetConfig>,
+
+    /// Ping backend to use: `fping` shells out to the fping binary, `icmp` drives
+    /// probes in-process with a pure-Rust async ICMP engine
+    #[structopt(long = "backend", default_value = "fping")]
+    backend: Backend,
+
+    /// Bucket boundaries (seconds) for the ping_rtt_seconds histogram
+    #[structopt(
+        long = "rtt-buckets",
+        use_delimiter = true,
+        default_value = "0.001,0.005,0.01,0.025,0.05,0.1,0.25,0.5,1.0"
+    )]
+    rtt_buckets: Vec<f64>,
 }
 
 fn index() -> impl Responder {
@@ -86,67 +92,9 @@ fn index() -> impl Responder {
 }
 
 fn metrics(result_store: web::Data<ResultStore>) -> impl Responder {
-    let mut output_string = String::new();
-
-    // measurements (min, avg max)
-    let ping_rtt = PrometheusMetric::new(
-        "ping_rtt_seconds",
-        MetricType::Gauge,
-        "Ping round trip time in seconds",
-    );
-
-    output_string.push_str(&ping_rtt.render_header());
-    let ping_results = &*result_store.ping_results.read();
-
-    for (_target, results) in ping_results.iter() {
-        for result in results {
-            if result.minimum.is_none() {
-                continue;
-            }
-            let ip = result.ip_address.to_owned().to_string();
-
-            let mut attributes = Vec::new();
-            attributes.push(("address", &ip[..]));
-            attributes.push(("sample", "minimum"));
-            output_string
-                .push_str(&ping_rtt.render_sample(Some(&attributes), result.minimum.unwrap()));
-
-            attributes = Vec::new();
-            attributes.push(("address", &ip[..]));
-            attributes.push(("sample", "average"));
-            output_string
-                .push_str(&ping_rtt.render_sample(Some(&attributes), result.average.unwrap()));
-
-            attributes = Vec::new();
-            attributes.push(("address", &ip[..]));
-            attributes.push(("sample", "maxiumum"));
-            output_string
-                .push_str(&ping_rtt.render_sample(Some(&attributes), result.maxiumum.unwrap()));
-        }
-    }
-
-    output_string.push_str("\n\n");
-
-    // packets lost as a percentage
-    let ping_packet_loss = PrometheusMetric::new(
-        "ping_packet_loss_percent",
-        MetricType::Gauge,
-        "Percent of ping packets lost",
-    );
-    output_string.push_str(&ping_packet_loss.render_header());
-
-    for (_target, results) in ping_results.iter() {
-        for result in results {
-            let ip = result.ip_address.to_owned().to_string();
-            let mut attributes = Vec::new();
-            attributes.push(("address", &ip[..]));
-            output_string
-                .push_str(&ping_packet_loss.render_sample(Some(&attributes), result.lost));
-        }
-    }
-
     HttpResponse::Ok()
-        .body(output_string)
+        .content_type("application/openmetrics-text; version=1.0.0; charset=utf-8")
+        .body(result_store.encode())
 }
 
 fn main() {
@@ -175,50 +123,68 @@ fn main() {
         ::std::process::exit(4);
     }
 
-    let results = Arc::new(RwLock::new(HashMap::new()));
-    let result_store = web::Data::new(ResultStore {
-        ping_results: results.clone()
-    });
-
-    let targets = Box::new(options.targets);
-    let static_targets: &'static Vec<IpNet> = Box::leak(targets);
+    for target in &options.targets {
+        if let Err(e) = target.validate() {
+            error!("invalid target config: {}", e);
+            ::std::process::exit(4);
+        }
+    }
 
-    // background threads to do the pings
-    for target in static_targets {
-        let results_arc = results.clone();
-        thread::spawn(move || {
+    let result_store = Arc::new(ResultStore::new(options.rtt_buckets.clone()));
 
-            // offset fping commands by some random amount of time
-            let mut rng = thread_rng();
-            let n = rng.gen_range(0, 60);
-            thread::sleep(Duration::from_secs(n));
+    let backend: Arc<dyn PingBackend> = match options.backend {
+        Backend::Fping => Arc::new(FpingBackend::default()),
+        Backend::Icmp => match IcmpBackend::new() {
+            Ok(backend) => Arc::new(backend),
+            Err(e) => {
+                error!("unable to start icmp backend: {}", e);
+                ::std::process::exit(4);
+            }
+        },
+    };
 
-            loop {
-                debug!("running {}", target);
-                let now = Instant::now();
+    let manager = Arc::new(TargetManager::new(backend, result_store.clone()));
+    manager.reconcile(options.targets.clone());
 
-                match process_subnet(*target) {
-                    Ok(subnet_results) => {
-                        let mut global_results = results_arc.write();
-                        global_results.remove(&target.to_string());
-                        global_results.insert(target.to_string(), subnet_results);
-                    },
-                    Err(e) => {
-                        error!("error {}", e);
-                        ::std::process::exit(4);
+    // re-read the config file and reconcile the running targets against it on SIGHUP,
+    // so adding or removing a subnet doesn't require restarting the exporter
+    {
+        let manager = manager.clone();
+        let config_path = options.config_path.clone();
+        thread::spawn(move || {
+            let mut signals =
+                Signals::new(&[SIGHUP]).expect("unable to register SIGHUP handler");
+            for _ in signals.forever() {
+                info!("SIGHUP received, reloading targets from {:?}", config_path);
+                match fs::read_to_string(&config_path) {
+                    Ok(file_contents) => match Opt::from_args_with_toml(&file_contents) {
+                        Ok(reloaded) => {
+                            match reloaded
+                                .targets
+                                .iter()
+                                .find_map(|target| target.validate().err())
+                            {
+                                Some(e) => error!(
+                                    "invalid target config on reload, keeping existing targets: {}",
+                                    e
+                                ),
+                                None => manager.reconcile(reloaded.targets),
+                            }
+                        }
+                        Err(e) => error!("error parsing config file on reload: {}", e),
                     },
+                    Err(e) => error!("error reading config file on reload: {}", e),
                 }
-
-                // only run once per minute
-                thread::sleep(Duration::from_secs(60 - now.elapsed().as_secs()));
             }
         });
     }
 
+    let result_store_data = web::Data::from(result_store);
+
     // start metrics server
     HttpServer::new(move || {
         App::new()
-            .register_data(result_store.clone())
+            .register_data(result_store_data.clone())
             .wrap(middleware::Compress::default())
             .route("/", web::get().to(index))
             .route("/metrics", web::get().to(metrics))
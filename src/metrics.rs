@@ -0,0 +1,334 @@
+use std::fmt::Error as FmtError;
+use std::sync::atomic::{AtomicI64, AtomicU64};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hashbrown::{HashMap, HashSet};
+use parking_lot::RwLock;
+use prometheus_client::encoding::{text::encode, EncodeLabel, EncodeLabelSet, LabelSetEncoder};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::Histogram;
+use prometheus_client::registry::Registry;
+
+use crate::ping_result::PingResult;
+use crate::target_config::TargetConfig;
+
+/// Labels attached to every sample for a target: the probed address plus
+/// whatever extra labels that target's config asked for. Built fresh from
+/// the target's (sorted) label map each time, so the same address/extras pair
+/// always hashes and compares equal across scrapes.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct AddressLabels {
+    pub address: String,
+    pub extra: Vec<(String, String)>,
+}
+
+impl AddressLabels {
+    fn new(address: String, target: &TargetConfig) -> Self {
+        let mut extra: Vec<(String, String)> = target
+            .labels
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        extra.sort();
+
+        AddressLabels { address, extra }
+    }
+}
+
+impl EncodeLabelSet for AddressLabels {
+    fn encode(&self, mut encoder: LabelSetEncoder) -> Result<(), FmtError> {
+        ("address", self.address.as_str()).encode(encoder.encode_label())?;
+
+        for (key, value) in &self.extra {
+            (key.as_str(), value.as_str()).encode(encoder.encode_label())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Labels for target-level (rather than per-host) metrics: which target the
+/// sample is about, plus that target's extra labels.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct TargetLabels {
+    pub target: String,
+    pub extra: Vec<(String, String)>,
+}
+
+impl TargetLabels {
+    fn new(target: &TargetConfig) -> Self {
+        let mut extra: Vec<(String, String)> = target
+            .labels
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        extra.sort();
+
+        TargetLabels {
+            target: target.subnet.to_string(),
+            extra,
+        }
+    }
+}
+
+impl EncodeLabelSet for TargetLabels {
+    fn encode(&self, mut encoder: LabelSetEncoder) -> Result<(), FmtError> {
+        ("target", self.target.as_str()).encode(encoder.encode_label())?;
+
+        for (key, value) in &self.extra {
+            (key.as_str(), value.as_str()).encode(encoder.encode_label())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Owns the Prometheus registry and every metric family the exporter emits.
+/// Results are fed in as they're produced (see `record`) rather than
+/// re-derived from scratch on every scrape, so the registry is the single
+/// source of truth for what `/metrics` renders.
+pub struct ResultStore {
+    registry: Registry,
+    /// Per-target set of `AddressLabels` currently published, so a removed
+    /// target's host-level series can be found and evicted from the
+    /// registry instead of staying frozen at their last value forever.
+    target_addresses: RwLock<HashMap<String, Vec<AddressLabels>>>,
+    ping_rtt_seconds: Family<AddressLabels, Histogram>,
+    ping_packet_loss_percent: Family<AddressLabels, Gauge<f64, AtomicU64>>,
+    ping_probes_sent_total: Family<AddressLabels, Counter>,
+    ping_probes_received_total: Family<AddressLabels, Counter>,
+    ping_target_last_success_timestamp_seconds: Family<TargetLabels, Gauge<f64, AtomicU64>>,
+    ping_target_scrape_errors_total: Family<TargetLabels, Counter>,
+    ping_target_up: Family<TargetLabels, Gauge<i64, AtomicI64>>,
+}
+
+impl ResultStore {
+    pub fn new(rtt_buckets: Vec<f64>) -> Self {
+        let mut registry = Registry::default();
+
+        let ping_rtt_seconds =
+            Family::<AddressLabels, Histogram>::new_with_constructor(move || {
+                Histogram::new(rtt_buckets.clone().into_iter())
+            });
+        registry.register(
+            "ping_rtt_seconds",
+            "Ping round trip time in seconds",
+            ping_rtt_seconds.clone(),
+        );
+
+        let ping_packet_loss_percent = Family::<AddressLabels, Gauge<f64, AtomicU64>>::default();
+        registry.register(
+            "ping_packet_loss_percent",
+            "Percent of ping packets lost",
+            ping_packet_loss_percent.clone(),
+        );
+
+        let ping_probes_sent_total = Family::<AddressLabels, Counter>::default();
+        registry.register(
+            "ping_probes_sent",
+            "Total number of ping probes sent",
+            ping_probes_sent_total.clone(),
+        );
+
+        let ping_probes_received_total = Family::<AddressLabels, Counter>::default();
+        registry.register(
+            "ping_probes_received",
+            "Total number of ping probes received",
+            ping_probes_received_total.clone(),
+        );
+
+        let ping_target_last_success_timestamp_seconds =
+            Family::<TargetLabels, Gauge<f64, AtomicU64>>::default();
+        registry.register(
+            "ping_target_last_success_timestamp_seconds",
+            "Unix timestamp of the last successful scrape of this target",
+            ping_target_last_success_timestamp_seconds.clone(),
+        );
+
+        let ping_target_scrape_errors_total = Family::<TargetLabels, Counter>::default();
+        registry.register(
+            "ping_target_scrape_errors",
+            "Total number of failed scrapes for this target",
+            ping_target_scrape_errors_total.clone(),
+        );
+
+        let ping_target_up = Family::<TargetLabels, Gauge<i64, AtomicI64>>::default();
+        registry.register(
+            "ping_target_up",
+            "Whether the last scrape of this target succeeded (1) or failed (0)",
+            ping_target_up.clone(),
+        );
+
+        ResultStore {
+            registry,
+            target_addresses: RwLock::new(HashMap::new()),
+            ping_rtt_seconds,
+            ping_packet_loss_percent,
+            ping_probes_sent_total,
+            ping_probes_received_total,
+            ping_target_last_success_timestamp_seconds,
+            ping_target_scrape_errors_total,
+            ping_target_up,
+        }
+    }
+
+    /// Feeds one target's freshly produced results into the registry.
+    pub fn record(&self, target: &TargetConfig, results: &[PingResult]) {
+        let mut addresses = Vec::with_capacity(results.len());
+
+        for result in results {
+            let labels = AddressLabels::new(result.ip_address.to_string(), target);
+
+            for &rtt in &result.rtts {
+                self.ping_rtt_seconds.get_or_create(&labels).observe(rtt);
+            }
+
+            self.ping_packet_loss_percent
+                .get_or_create(&labels)
+                .set(result.lost as f64);
+
+            self.ping_probes_sent_total
+                .get_or_create(&labels)
+                .inc_by(result.sent as u64);
+            self.ping_probes_received_total
+                .get_or_create(&labels)
+                .inc_by(result.received as u64);
+
+            addresses.push(labels);
+        }
+
+        // merge rather than replace, so a host that dropped out of this scrape
+        // is still tracked and gets evicted when the target is removed
+        let mut target_addresses = self.target_addresses.write();
+        let known = target_addresses
+            .entry(target.subnet.to_string())
+            .or_insert_with(Vec::new);
+        let mut seen: HashSet<AddressLabels> = known.iter().cloned().collect();
+        for labels in addresses {
+            if seen.insert(labels.clone()) {
+                known.push(labels);
+            }
+        }
+    }
+
+    /// Removes every per-host series this target ever produced from the
+    /// registry, so a decommissioned target's host metrics actually
+    /// disappear from `/metrics` instead of staying frozen at their last
+    /// value.
+    pub fn remove_addresses(&self, target: &TargetConfig) {
+        let key = target.subnet.to_string();
+
+        if let Some(addresses) = self.target_addresses.write().remove(&key) {
+            for labels in &addresses {
+                self.ping_rtt_seconds.remove(labels);
+                self.ping_packet_loss_percent.remove(labels);
+                self.ping_probes_sent_total.remove(labels);
+                self.ping_probes_received_total.remove(labels);
+            }
+        }
+    }
+
+    /// Removes this target's own freshness/health series (last success
+    /// timestamp, scrape error count, up/down gauge). Without this, a
+    /// removed target would keep reporting `ping_target_up` frozen at
+    /// whatever it last was, with no way to tell the series is dead rather
+    /// than just old.
+    pub fn remove_target_labels(&self, target: &TargetConfig) {
+        let labels = TargetLabels::new(target);
+
+        self.ping_target_last_success_timestamp_seconds.remove(&labels);
+        self.ping_target_scrape_errors_total.remove(&labels);
+        self.ping_target_up.remove(&labels);
+    }
+
+    /// Marks a target as having just scraped successfully: bumps its
+    /// freshness timestamp and flips `ping_target_up` to 1.
+    pub fn note_success(&self, target: &TargetConfig) {
+        let labels = TargetLabels::new(target);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        self.ping_target_last_success_timestamp_seconds
+            .get_or_create(&labels)
+            .set(now);
+        self.ping_target_up.get_or_create(&labels).set(1);
+    }
+
+    /// Marks a target as having just failed to scrape: counts the error and
+    /// flips `ping_target_up` to 0, without touching the freshness timestamp
+    /// so `time() - ping_target_last_success_timestamp_seconds` keeps growing.
+    pub fn note_failure(&self, target: &TargetConfig) {
+        let labels = TargetLabels::new(target);
+
+        self.ping_target_scrape_errors_total
+            .get_or_create(&labels)
+            .inc();
+        self.ping_target_up.get_or_create(&labels).set(0);
+    }
+
+    pub fn encode(&self) -> String {
+        let mut buffer = String::new();
+        encode(&mut buffer, &self.registry).unwrap();
+        buffer
+    }
+}
+
+#[test]
+fn test_record_then_remove_addresses() {
+    let target = TargetConfig {
+        subnet: "10.90.0.0/30".parse().unwrap(),
+        probe_count: 1,
+        probe_interval_ms: 1,
+        probe_timeout_ms: 1,
+        scrape_period_secs: 1,
+        labels: HashMap::new(),
+    };
+    let result = PingResult {
+        ip_address: "10.90.0.1".parse().unwrap(),
+        sent: 1,
+        received: 1,
+        lost: 0,
+        minimum: Some(0.01),
+        average: Some(0.01),
+        maxiumum: Some(0.01),
+        rtts: vec![0.01],
+    };
+
+    let store = ResultStore::new(vec![0.01, 0.1]);
+    store.record(&target, &[result]);
+    assert!(store.encode().contains("10.90.0.1"));
+
+    store.remove_addresses(&target);
+    assert!(!store.encode().contains("10.90.0.1"));
+}
+
+#[test]
+fn test_note_success_and_failure_then_remove_target_labels() {
+    let target = TargetConfig {
+        subnet: "10.90.0.4/30".parse().unwrap(),
+        probe_count: 1,
+        probe_interval_ms: 1,
+        probe_timeout_ms: 1,
+        scrape_period_secs: 1,
+        labels: HashMap::new(),
+    };
+
+    let store = ResultStore::new(vec![0.01]);
+
+    store.note_success(&target);
+    let after_success = store.encode();
+    assert!(after_success.contains("ping_target_up"));
+    assert!(after_success.contains("ping_target_up{target=\"10.90.0.4/30\"} 1"));
+
+    store.note_failure(&target);
+    let after_failure = store.encode();
+    assert!(after_failure.contains("ping_target_scrape_errors_total{target=\"10.90.0.4/30\"} 1"));
+    assert!(after_failure.contains("ping_target_up{target=\"10.90.0.4/30\"} 0"));
+
+    store.remove_target_labels(&target);
+    assert!(!store.encode().contains("10.90.0.4/30"));
+}
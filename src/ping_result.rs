@@ -5,6 +5,15 @@ use std::str::FromStr;
 use lazy_static::lazy_static;
 use snafu::{OptionExt, ResultExt, Snafu};
 
+/// fping6 brackets IPv6 addresses in its output (`[::1] : ...`); fping
+/// doesn't. Strip the brackets, if present, before parsing as an `IpAddr`.
+fn strip_brackets(ip_address_output: &str) -> &str {
+    ip_address_output
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(ip_address_output)
+}
+
 lazy_static! {
     static ref FPING_REGEX: Regex = Regex::new(
         r"(?P<ip_address>.*) :.*= (?P<sent>\d+)/(?P<received>\d+)/(?P<lost>\d+)%(?:,.*= (?P<min>\d+\.?\d*)/(?P<avg>\d+\.?\d*)/(?P<max>\d+\.?\d*))?"
@@ -41,12 +50,19 @@ pub struct PingResult {
     pub minimum: Option<f64>,
     pub average: Option<f64>,
     pub maxiumum: Option<f64>,
+    /// Individual round trip times (seconds) for each received probe, in the
+    /// order fping reported them. Empty when parsed from a summary-only line.
+    pub rtts: Vec<f64>,
 }
 
 impl FromStr for PingResult {
     type Err = FpingParseError;
 
     fn from_str(ping_result: &str) -> Result<Self, Self::Err> {
+        if !FPING_REGEX.is_match(ping_result) {
+            return parse_count_mode(ping_result);
+        }
+
         let caps = FPING_REGEX.captures(&ping_result).unwrap();
 
         let ip_address_output = caps
@@ -56,6 +72,7 @@ impl FromStr for PingResult {
             })?
             .as_str()
             .trim();
+        let ip_address_output = strip_brackets(ip_address_output);
 
         let ip_address: IpAddr = ip_address_output
             .parse()
@@ -128,10 +145,68 @@ impl FromStr for PingResult {
             minimum,
             average,
             maxiumum,
+            rtts: vec![],
         })
     }
 }
 
+/// Parses an `fping -C <count>` line, e.g.
+/// `8.8.8.8 : 0.37 0.43 - 0.41 0.39`, where each token is a per-probe round
+/// trip time in milliseconds and `-` marks a dropped probe.
+fn parse_count_mode(ping_result: &str) -> Result<PingResult, FpingParseError> {
+    let mut parts = ping_result.splitn(2, " : ");
+
+    let ip_address_output = parts.next().context(CaptureRegex)?.trim();
+    let ip_address_output = strip_brackets(ip_address_output);
+    let rtt_tokens = parts.next().context(CaptureRegex)?;
+
+    let ip_address: IpAddr = ip_address_output
+        .parse()
+        .context(IpAddressError { ip_address_output })?;
+
+    let mut rtts = vec![];
+    let mut sent: u8 = 0;
+    let mut received: u8 = 0;
+
+    for token in rtt_tokens.split_whitespace() {
+        sent += 1;
+
+        if token == "-" {
+            continue;
+        }
+
+        let rtt_ms: f64 = token.parse().context(ParseFloatError)?;
+        rtts.push(rtt_ms / 1000.0);
+        received += 1;
+    }
+
+    let lost = if sent == 0 {
+        0
+    } else {
+        (((sent - received) as u32 * 100) / sent as u32) as u8
+    };
+
+    let (minimum, average, maxiumum) = if rtts.is_empty() {
+        (None, None, None)
+    } else {
+        let min = rtts.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = rtts.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let avg = rtts.iter().sum::<f64>() / rtts.len() as f64;
+        (Some(min), Some(avg), Some(max))
+    };
+
+    Ok(PingResult {
+        ip_address,
+        sent,
+        received,
+        lost,
+        minimum,
+        average,
+        maxiumum,
+        rtts,
+    })
+}
+
 #[test]
 fn test_no_loss() {
     let input = "1.1.1.1 : xmt/rcv/%loss = 2/2/0%, min/avg/max = 0.70/0.90/1.10";
@@ -163,4 +238,56 @@ fn test_loss() {
     assert!(result.minimum.is_none());
     assert!(result.average.is_none());
     assert!(result.maxiumum.is_none());
+}
+
+#[test]
+fn test_count_mode() {
+    let input = "8.8.8.8 : 0.37 0.43 - 0.41 0.39";
+    let result: PingResult = input.parse().unwrap();
+
+    assert_eq!(result.ip_address, Ipv4Addr::new(8, 8, 8, 8));
+
+    assert_eq!(result.sent, 5);
+    assert_eq!(result.received, 4);
+    assert_eq!(result.lost, 20);
+
+    assert_eq!(result.rtts, vec![0.00037, 0.00043, 0.00041, 0.00039]);
+}
+
+#[test]
+fn test_ipv6_summary_mode() {
+    let input = "2606:4700:4700::1111 : xmt/rcv/%loss = 5/5/0%, min/avg/max = 1.2/1.4/1.9";
+    let result: PingResult = input.parse().unwrap();
+
+    assert_eq!(
+        result.ip_address,
+        "2606:4700:4700::1111".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(result.sent, 5);
+    assert_eq!(result.received, 5);
+    assert_eq!(result.lost, 0);
+}
+
+#[test]
+fn test_ipv6_bracketed() {
+    let input = "[2606:4700:4700::1111] : xmt/rcv/%loss = 5/5/0%, min/avg/max = 1.2/1.4/1.9";
+    let result: PingResult = input.parse().unwrap();
+
+    assert_eq!(
+        result.ip_address,
+        "2606:4700:4700::1111".parse::<IpAddr>().unwrap()
+    );
+}
+
+#[test]
+fn test_ipv6_count_mode() {
+    let input = "[2606:4700:4700::1111] : 1.2 1.4 - 1.9";
+    let result: PingResult = input.parse().unwrap();
+
+    assert_eq!(
+        result.ip_address,
+        "2606:4700:4700::1111".parse::<IpAddr>().unwrap()
+    );
+    assert_eq!(result.sent, 4);
+    assert_eq!(result.received, 3);
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+use hashbrown::HashMap;
+use ipnet::IpNet;
+use serde_derive::Deserialize;
+
+fn default_probe_count() -> u8 {
+    5
+}
+
+fn default_probe_interval_ms() -> u64 {
+    25
+}
+
+fn default_probe_timeout_ms() -> u64 {
+    500
+}
+
+fn default_scrape_period_secs() -> u64 {
+    60
+}
+
+/// Per-target scrape configuration loaded from the `[[targets]]` table in the
+/// TOML config file. Lets an operator tune how aggressively each subnet is
+/// probed instead of sharing one set of fping flags across every target.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TargetConfig {
+    pub subnet: IpNet,
+
+    /// Number of probes sent per host each scrape.
+    #[serde(default = "default_probe_count")]
+    pub probe_count: u8,
+
+    /// Delay between successive probes to the same host, in milliseconds.
+    #[serde(default = "default_probe_interval_ms")]
+    pub probe_interval_ms: u64,
+
+    /// Timeout waiting for a reply to a single probe, in milliseconds.
+    #[serde(default = "default_probe_timeout_ms")]
+    pub probe_timeout_ms: u64,
+
+    /// How often this target is scraped, in seconds.
+    #[serde(default = "default_scrape_period_secs")]
+    pub scrape_period_secs: u64,
+
+    /// Extra Prometheus labels attached to every sample this target emits.
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+/// Label keys `metrics.rs` always attaches itself (`address` on per-host
+/// series, `target` on per-target series). An operator-supplied label with
+/// one of these names would collide with the built-in one and produce a
+/// sample with a duplicate label name, which is invalid OpenMetrics
+/// exposition.
+const RESERVED_LABELS: &[&str] = &["address", "target"];
+
+impl TargetConfig {
+    /// Checks that none of this target's extra labels collide with a
+    /// built-in label name.
+    pub fn validate(&self) -> Result<(), String> {
+        for key in self.labels.keys() {
+            if RESERVED_LABELS.contains(&key.as_str()) {
+                return Err(format!(
+                    "target {}: label `{}` is reserved and cannot be overridden",
+                    self.subnet, key
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_validate_rejects_reserved_label() {
+    let mut labels = HashMap::new();
+    labels.insert("address".to_string(), "evil".to_string());
+
+    let target = TargetConfig {
+        subnet: "10.0.0.0/30".parse().unwrap(),
+        probe_count: 1,
+        probe_interval_ms: 1,
+        probe_timeout_ms: 1,
+        scrape_period_secs: 1,
+        labels,
+    };
+
+    assert!(target.validate().is_err());
+}
+
+#[test]
+fn test_validate_accepts_ordinary_labels() {
+    let mut labels = HashMap::new();
+    labels.insert("site".to_string(), "dc1".to_string());
+
+    let target = TargetConfig {
+        subnet: "10.0.0.0/30".parse().unwrap(),
+        probe_count: 1,
+        probe_interval_ms: 1,
+        probe_timeout_ms: 1,
+        scrape_period_secs: 1,
+        labels,
+    };
+
+    assert!(target.validate().is_ok());
+}
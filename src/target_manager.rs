@@ -0,0 +1,221 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+use parking_lot::RwLock;
+use rand::{thread_rng, Rng};
+
+use crate::metrics::ResultStore;
+use crate::ping_backend::PingBackend;
+#[cfg(test)]
+use crate::ping_result::PingResult;
+use crate::target_config::TargetConfig;
+
+struct TargetHandle {
+    stop: Arc<AtomicBool>,
+    config: TargetConfig,
+    #[allow(dead_code)]
+    thread: JoinHandle<()>,
+}
+
+/// Owns one background scrape loop per configured target, keyed by subnet
+/// string, and lets the running set be changed at runtime via `reconcile`
+/// instead of only at process startup. Added targets get a new loop spawned;
+/// removed targets are signalled to stop and pruned from `ResultStore` so
+/// their series disappear from `/metrics`.
+pub struct TargetManager {
+    backend: Arc<dyn PingBackend>,
+    result_store: Arc<ResultStore>,
+    handles: RwLock<HashMap<String, TargetHandle>>,
+}
+
+impl TargetManager {
+    pub fn new(backend: Arc<dyn PingBackend>, result_store: Arc<ResultStore>) -> Self {
+        TargetManager {
+            backend,
+            result_store,
+            handles: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Brings the running set of scrape loops in line with `new_targets`:
+    /// spawns a loop for any target not already running, stops + prunes any
+    /// running target that's no longer present, and restarts (stop + spawn)
+    /// any target whose config changed, since a loop already running on a
+    /// stale `TargetConfig` would otherwise never pick up the new settings.
+    pub fn reconcile(&self, new_targets: Vec<TargetConfig>) {
+        let mut handles = self.handles.write();
+
+        let new_by_key: HashMap<String, TargetConfig> = new_targets
+            .into_iter()
+            .map(|target| (target.subnet.to_string(), target))
+            .collect();
+
+        let removed: Vec<String> = handles
+            .iter()
+            .filter(|(key, handle)| match new_by_key.get(*key) {
+                Some(target) => target != &handle.config,
+                None => true,
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in removed {
+            if let Some(handle) = handles.remove(&key) {
+                handle.stop.store(true, Ordering::SeqCst);
+                self.result_store.remove_addresses(&handle.config);
+                self.result_store.remove_target_labels(&handle.config);
+            }
+            info!("stopped scraping {}", key);
+        }
+
+        for (key, target) in new_by_key {
+            if handles.contains_key(&key) {
+                continue;
+            }
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let config = target.clone();
+            let thread = self.spawn_target(target, stop.clone());
+            handles.insert(key, TargetHandle { stop, config, thread });
+        }
+    }
+
+    fn spawn_target(&self, target: TargetConfig, stop: Arc<AtomicBool>) -> JoinHandle<()> {
+        let backend = self.backend.clone();
+        let result_store = self.result_store.clone();
+        let scrape_period = Duration::from_secs(target.scrape_period_secs);
+
+        thread::spawn(move || {
+            // offset fping commands by some random amount of time
+            let mut rng = thread_rng();
+            let n = rng.gen_range(0, target.scrape_period_secs.max(1));
+            thread::sleep(Duration::from_secs(n));
+
+            while !stop.load(Ordering::SeqCst) {
+                debug!("running {}", target.subnet);
+                let now = Instant::now();
+
+                let scrape_result = backend.ping_target(&target);
+
+                // `stop` may have flipped while the scrape above was in
+                // flight (reconcile already pruned this target's series by
+                // now) - re-check before writing back, or a scrape that
+                // outlives its removal resurrects the series we just pruned.
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match scrape_result {
+                    Ok(subnet_results) => {
+                        result_store.record(&target, &subnet_results);
+                        result_store.note_success(&target);
+                    }
+                    Err(e) => {
+                        // keep serving the other targets and the last good
+                        // results for this one; ping_target_up and friends
+                        // tell Prometheus this target is now stale
+                        error!("error scraping {}: {}", target.subnet, e);
+                        result_store.note_failure(&target);
+                    }
+                }
+
+                let elapsed = now.elapsed();
+                if elapsed < scrape_period {
+                    thread::sleep(scrape_period - elapsed);
+                }
+            }
+
+            debug!("stopped scraping {}", target.subnet);
+        })
+    }
+}
+
+/// Instant `PingBackend` used only by the tests below, so `reconcile`'s
+/// scrape loops run as fast as the test's own polling instead of waiting on
+/// a real fping/icmp round trip.
+#[cfg(test)]
+struct FakeBackend;
+
+#[cfg(test)]
+impl PingBackend for FakeBackend {
+    fn ping_target(&self, target: &TargetConfig) -> Result<Vec<PingResult>, String> {
+        Ok(vec![PingResult {
+            ip_address: target.subnet.hosts().next().unwrap(),
+            sent: 1,
+            received: 1,
+            lost: 0,
+            minimum: Some(0.01),
+            average: Some(0.01),
+            maxiumum: Some(0.01),
+            rtts: vec![0.01],
+        }])
+    }
+}
+
+#[cfg(test)]
+fn wait_until<F: Fn() -> bool>(timeout: Duration, check: F) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if check() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn test_reconcile_add_edit_remove() {
+    let backend: Arc<dyn PingBackend> = Arc::new(FakeBackend);
+    let result_store = Arc::new(ResultStore::new(vec![0.01, 0.1]));
+    let manager = TargetManager::new(backend, result_store.clone());
+
+    let mut target = TargetConfig {
+        subnet: "10.99.0.0/30".parse().unwrap(),
+        probe_count: 1,
+        probe_interval_ms: 0,
+        probe_timeout_ms: 0,
+        scrape_period_secs: 0,
+        labels: HashMap::new(),
+    };
+
+    manager.reconcile(vec![target.clone()]);
+    assert!(
+        wait_until(Duration::from_secs(2), || result_store
+            .encode()
+            .contains("10.99.0.1")),
+        "expected the new target's host series to appear after add"
+    );
+
+    // editing a running target's config (same subnet, new label) should
+    // stop the old loop and respawn it rather than leaving it running with
+    // its stale config.
+    target.labels.insert("env".to_string(), "test".to_string());
+    manager.reconcile(vec![target.clone()]);
+    assert!(
+        wait_until(Duration::from_secs(2), || result_store
+            .encode()
+            .contains("env=\"test\"")),
+        "expected the edited target's new label to appear after restart"
+    );
+
+    // removing the target entirely should prune its series, and it should
+    // stay pruned - a scrape from the old loop that was in flight when it
+    // was stopped must not be allowed to write the series back.
+    manager.reconcile(vec![]);
+    assert!(
+        wait_until(Duration::from_secs(2), || !result_store
+            .encode()
+            .contains("10.99.0.1")),
+        "expected the removed target's series to disappear"
+    );
+    thread::sleep(Duration::from_millis(200));
+    assert!(
+        !result_store.encode().contains("10.99.0.1"),
+        "removed target's series came back, a stale scrape must have written through after removal"
+    );
+}